@@ -1,12 +1,17 @@
 use std::fs::File;
 use std::path::PathBuf;
+use std::thread;
 use std::time::{Duration, SystemTime};
 
-use anyhow::{anyhow, Result};
+use anyhow::Result;
 use fs2::FileExt;
 use structopt::{clap, StructOpt};
 
-use crate::Manifest;
+use crate::error::Ros2wsError::LockTimeout;
+use crate::{discover_crates, Manifest, PatchEntry};
+
+const INITIAL_BACKOFF: Duration = Duration::from_millis(5);
+const MAX_BACKOFF: Duration = Duration::from_millis(250);
 
 #[derive(Debug, StructOpt)]
 #[structopt(about, bin_name("cargo-ros2ws"))]
@@ -20,12 +25,16 @@ pub struct Cargo {
 }
 
 impl Cargo {
-    pub fn execute(self) -> Result<()> {
+    /// Runs the requested subcommand, returning whether `--dry-run` found pending changes.
+    pub fn execute(self) -> Result<bool> {
         match self.cmd {
             SubCommand::AddMember(cmd) => cmd.execute(&self.common_args),
             SubCommand::AddPatch(cmd) => cmd.execute(&self.common_args),
-        }?;
-        Ok(())
+            SubCommand::Discover(cmd) => cmd.execute(&self.common_args),
+            SubCommand::RemoveMember(cmd) => cmd.execute(&self.common_args),
+            SubCommand::RemovePatch(cmd) => cmd.execute(&self.common_args),
+            SubCommand::Prune(cmd) => cmd.execute(&self.common_args),
+        }
     }
 }
 
@@ -39,9 +48,36 @@ struct CommonArgs {
     #[structopt(long)]
     with_lock: bool,
 
-    /// How many seconds to wait for acquire lock (0 means forever)
+    /// Take a shared lock instead of an exclusive one, e.g. for read-only operations
+    #[structopt(long)]
+    shared: bool,
+
+    /// How many seconds to wait to acquire the lock (0 means forever)
     #[structopt(short = "s", long, default_value = "0")]
-    wait_nsecs: u64,
+    wait_secs: u64,
+
+    /// Print a unified diff of the manifest edits instead of writing them, exiting with a
+    /// distinct status if changes would have been made
+    #[structopt(long)]
+    dry_run: bool,
+}
+
+impl CommonArgs {
+    /// Writes `manifest`, or under `--dry-run` prints a diff instead. Returns whether there
+    /// were changes pending that a dry run declined to write, so the caller can propagate a
+    /// distinct process exit status once the call stack has unwound.
+    fn finish(&self, manifest: &Manifest) -> Result<bool> {
+        if self.dry_run {
+            if manifest.has_changes() {
+                print!("{}", manifest.diff());
+                return Ok(true);
+            }
+            return Ok(false);
+        }
+
+        manifest.write_to(&self.manifest_path)?;
+        Ok(false)
+    }
 }
 
 struct FileLock {
@@ -55,20 +91,28 @@ impl FileLock {
         }
 
         let file = File::open(&args.manifest_path)?;
-        let deadline = Duration::from_secs(args.wait_nsecs);
+        let deadline = Duration::from_secs(args.wait_secs);
         let timer = SystemTime::now();
-        while args.wait_nsecs == 0 || timer.elapsed()? <= deadline {
-            if file.try_lock_exclusive().is_ok() {
+        let mut backoff = INITIAL_BACKOFF;
+        loop {
+            let acquired = if args.shared {
+                file.try_lock_shared()
+            } else {
+                file.try_lock_exclusive()
+            };
+            if acquired.is_ok() {
                 return Ok(Self {
                     locking_file: Some(file),
                 });
             }
-        }
 
-        Err(anyhow!(
-            "Failed to aqcuire lock of file {}",
-            args.manifest_path.display()
-        ))
+            if args.wait_secs != 0 && timer.elapsed()? > deadline {
+                return Err(LockTimeout(args.manifest_path.clone()).into());
+            }
+
+            thread::sleep(backoff);
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
     }
 }
 
@@ -89,6 +133,22 @@ enum SubCommand {
     /// Override dependencies using [patch] section
     #[structopt(setting(clap::AppSettings::ColoredHelp))]
     AddPatch(CargoAddPatch),
+
+    /// Recursively discover crates under a directory and add them all as members
+    #[structopt(setting(clap::AppSettings::ColoredHelp))]
+    Discover(CargoDiscover),
+
+    /// Remove a crate from the members of cargo workspace
+    #[structopt(setting(clap::AppSettings::ColoredHelp))]
+    RemoveMember(CargoRemoveMember),
+
+    /// Remove an override from the [patch] section
+    #[structopt(setting(clap::AppSettings::ColoredHelp))]
+    RemovePatch(CargoRemovePatch),
+
+    /// Drop members whose path no longer exists on disk
+    #[structopt(setting(clap::AppSettings::ColoredHelp))]
+    Prune(CargoPrune),
 }
 
 #[derive(Debug, StructOpt)]
@@ -98,13 +158,12 @@ struct CargoAddMember {
 }
 
 impl CargoAddMember {
-    fn execute(self, args: &CommonArgs) -> Result<()> {
+    fn execute(self, args: &CommonArgs) -> Result<bool> {
         let _lock = FileLock::from_cli_args(args)?;
 
         let mut manifest = Manifest::read_from(&args.manifest_path)?;
         manifest.add_member(self.member)?;
-        manifest.write_to(&args.manifest_path)?;
-        Ok(())
+        args.finish(&manifest)
     }
 }
 
@@ -114,18 +173,271 @@ struct CargoAddPatch {
     #[structopt(short, long = "crate")]
     crate_name: String,
 
+    /// Source to override, e.g. `crates-io`, a registry name, or a source URL like
+    /// `https://github.com/foo/bar`
+    #[structopt(long, default_value = "crates-io")]
+    source: String,
+
     /// Absolute path to the crate to override with
     #[structopt(short, long)]
-    path: PathBuf,
+    path: Option<PathBuf>,
+
+    /// Git repository to override with
+    #[structopt(long)]
+    git: Option<String>,
+
+    /// Git branch to use, requires `--git`
+    #[structopt(long)]
+    branch: Option<String>,
+
+    /// Git tag to use, requires `--git`
+    #[structopt(long)]
+    tag: Option<String>,
+
+    /// Git revision to use, requires `--git`
+    #[structopt(long)]
+    rev: Option<String>,
+
+    /// Version requirement to override with
+    #[structopt(long)]
+    version: Option<String>,
 }
 
 impl CargoAddPatch {
-    fn execute(self, args: &CommonArgs) -> Result<()> {
+    fn execute(self, args: &CommonArgs) -> Result<bool> {
+        let _lock = FileLock::from_cli_args(args)?;
+
+        let entry = PatchEntry {
+            path: self.path,
+            git: self.git,
+            branch: self.branch,
+            tag: self.tag,
+            rev: self.rev,
+            version: self.version,
+        };
+
+        let mut manifest = Manifest::read_from(&args.manifest_path)?;
+        manifest.add_patch(&self.source, &self.crate_name, &entry)?;
+        args.finish(&manifest)
+    }
+}
+
+#[derive(Debug, StructOpt)]
+struct CargoDiscover {
+    /// Absolute path to the root directory to recursively search for crates
+    root: PathBuf,
+
+    /// Additionally path-patch each discovered crate by its package name, so overlay
+    /// workspaces resolve to the discovered local sources
+    #[structopt(long)]
+    patch: bool,
+}
+
+impl CargoDiscover {
+    fn execute(self, args: &CommonArgs) -> Result<bool> {
+        let _lock = FileLock::from_cli_args(args)?;
+
+        let crates = discover_crates(&self.root)?;
+
+        let mut manifest = Manifest::read_from(&args.manifest_path)?;
+        for krate in &crates {
+            manifest.add_member(&krate.path)?;
+            if self.patch {
+                let entry = PatchEntry {
+                    path: Some(krate.path.clone()),
+                    ..PatchEntry::default()
+                };
+                manifest.add_patch("crates-io", &krate.name, &entry)?;
+            }
+        }
+        args.finish(&manifest)
+    }
+}
+
+#[derive(Debug, StructOpt)]
+struct CargoRemoveMember {
+    /// Absolute path to the crate to remove from the members of cargo workspace
+    member: PathBuf,
+}
+
+impl CargoRemoveMember {
+    fn execute(self, args: &CommonArgs) -> Result<bool> {
         let _lock = FileLock::from_cli_args(args)?;
 
         let mut manifest = Manifest::read_from(&args.manifest_path)?;
-        manifest.add_patch(&self.crate_name, self.path)?;
-        manifest.write_to(&args.manifest_path)?;
+        manifest.remove_member(self.member)?;
+        args.finish(&manifest)
+    }
+}
+
+#[derive(Debug, StructOpt)]
+struct CargoRemovePatch {
+    /// Crate name to remove the override for
+    #[structopt(short, long = "crate")]
+    crate_name: String,
+
+    /// Source to remove the override from
+    #[structopt(long, default_value = "crates-io")]
+    source: String,
+}
+
+impl CargoRemovePatch {
+    fn execute(self, args: &CommonArgs) -> Result<bool> {
+        let _lock = FileLock::from_cli_args(args)?;
+
+        let mut manifest = Manifest::read_from(&args.manifest_path)?;
+        manifest.remove_patch(&self.source, &self.crate_name)?;
+        args.finish(&manifest)
+    }
+}
+
+#[derive(Debug, StructOpt)]
+struct CargoPrune {}
+
+impl CargoPrune {
+    fn execute(self, args: &CommonArgs) -> Result<bool> {
+        let _lock = FileLock::from_cli_args(args)?;
+
+        let mut manifest = Manifest::read_from(&args.manifest_path)?;
+        manifest.prune_members()?;
+        args.finish(&manifest)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::fs;
+
+    use tempfile::TempDir;
+
+    const MANIFEST_FILENAME: &str = "Cargo.toml";
+
+    fn common_args(
+        manifest_path: PathBuf,
+        with_lock: bool,
+        shared: bool,
+        wait_secs: u64,
+    ) -> CommonArgs {
+        CommonArgs {
+            manifest_path,
+            with_lock,
+            shared,
+            wait_secs,
+            dry_run: false,
+        }
+    }
+
+    #[test]
+    fn test_file_lock_without_with_lock_is_noop() -> Result<()> {
+        // a path that does not exist would make `File::open` fail, proving this never opens it
+        let args = common_args(PathBuf::from("/does/not/exist"), false, false, 0);
+        let lock = FileLock::from_cli_args(&args)?;
+        assert!(lock.locking_file.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_lock_shared_locks_do_not_contend() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let file = temp_dir.path().join(MANIFEST_FILENAME);
+        fs::write(&file, "")?;
+
+        let held = File::open(&file)?;
+        held.lock_shared()?;
+
+        let args = common_args(file, true, true, 1);
+        let lock = FileLock::from_cli_args(&args)?;
+        assert!(lock.locking_file.is_some());
+
+        held.unlock()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_lock_exclusive_times_out() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let file = temp_dir.path().join(MANIFEST_FILENAME);
+        fs::write(&file, "")?;
+
+        let held = File::open(&file)?;
+        held.lock_exclusive()?;
+
+        let args = common_args(file, true, false, 1);
+        let err = FileLock::from_cli_args(&args).unwrap_err();
+        assert!(err.downcast_ref::<crate::error::Ros2wsError>().is_some());
+
+        held.unlock()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_common_args_finish_dry_run_does_not_write() -> Result<()> {
+        static CONTENT: &str = "[workspace]\nmembers = [\"/test1\"]\n";
+
+        let temp_dir = TempDir::new()?;
+        let file = temp_dir.path().join(MANIFEST_FILENAME);
+        fs::write(&file, CONTENT)?;
+
+        let mut manifest = Manifest::read_from(&file)?;
+        manifest.add_member(PathBuf::from("/test2"))?;
+
+        let args = CommonArgs {
+            manifest_path: file.clone(),
+            with_lock: false,
+            shared: false,
+            wait_secs: 0,
+            dry_run: true,
+        };
+        assert!(args.finish(&manifest)?);
+        assert_eq!(fs::read_to_string(&file)?, CONTENT);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_common_args_finish_dry_run_no_changes() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let file = temp_dir.path().join(MANIFEST_FILENAME);
+        fs::write(&file, "")?;
+
+        let manifest = Manifest::read_from(&file)?;
+        let args = CommonArgs {
+            manifest_path: file,
+            with_lock: false,
+            shared: false,
+            wait_secs: 0,
+            dry_run: true,
+        };
+        assert!(!args.finish(&manifest)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_common_args_finish_writes_when_not_dry_run() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let file = temp_dir.path().join(MANIFEST_FILENAME);
+        fs::write(&file, "")?;
+
+        let mut manifest = Manifest::read_from(&file)?;
+        manifest.add_member(PathBuf::from("/test1"))?;
+
+        let args = CommonArgs {
+            manifest_path: file.clone(),
+            with_lock: false,
+            shared: false,
+            wait_secs: 0,
+            dry_run: false,
+        };
+        assert!(!args.finish(&manifest)?);
+        assert_eq!(
+            fs::read_to_string(&file)?,
+            "\n[workspace]\nmembers = [\"/test1\"]\n"
+        );
+
         Ok(())
     }
 }
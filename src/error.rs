@@ -2,4 +2,7 @@
 pub enum Ros2wsError {
     #[error("the type of the data for key `{0}` should be {1}")]
     InvalidManifestFile(String, String),
+
+    #[error("timed out waiting to acquire lock of file {0}")]
+    LockTimeout(std::path::PathBuf),
 }
@@ -7,7 +7,8 @@
 )]
 #![allow(clippy::module_name_repetitions)]
 
-use anyhow::Result;
+use std::process::ExitCode;
+
 use structopt::StructOpt as _;
 
 mod cli;
@@ -15,9 +16,19 @@ mod error;
 mod manifest;
 
 use cli::Cargo;
-use manifest::Manifest;
+use manifest::{discover_crates, Manifest, PatchEntry};
+
+/// Exit status for `--dry-run` when the manifest would have changed, so scripts can use it as a
+/// verification gate in CI.
+const DRY_RUN_CHANGED_EXIT_CODE: u8 = 2;
 
-fn main() -> Result<()> {
-    Cargo::from_args().execute()?;
-    Ok(())
+fn main() -> ExitCode {
+    match Cargo::from_args().execute() {
+        Ok(true) => ExitCode::from(DRY_RUN_CHANGED_EXIT_CODE),
+        Ok(false) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {err:?}");
+            ExitCode::FAILURE
+        }
+    }
 }
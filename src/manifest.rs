@@ -1,7 +1,8 @@
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use anyhow::{anyhow, ensure, Context as _, Result};
+use similar::TextDiff;
 use toml_edit::{self, Document};
 
 use crate::error::Ros2wsError::InvalidManifestFile;
@@ -10,10 +11,81 @@ const WORKSPACE_KEY: &str = "workspace";
 const MEMBERS_KEY: &str = "members";
 const PATCH_KEY: &str = "patch";
 const CRATES_IO_KEY: &str = "crates-io";
+const PACKAGE_KEY: &str = "package";
+const PACKAGE_NAME_KEY: &str = "name";
+const TARGET_DIR_NAME: &str = "target";
+
+/// A single entry to be written into a `[patch.<source>]` table.
+///
+/// Exactly one of `path`/`git`/`version` is expected to make sense in practice, and
+/// `branch`/`tag`/`rev` only make sense alongside `git`; use [`PatchEntry::validate`] to reject
+/// nonsensical combinations before writing them out.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct PatchEntry {
+    pub(crate) path: Option<PathBuf>,
+    pub(crate) git: Option<String>,
+    pub(crate) branch: Option<String>,
+    pub(crate) tag: Option<String>,
+    pub(crate) rev: Option<String>,
+    pub(crate) version: Option<String>,
+}
+
+impl PatchEntry {
+    fn validate(&self) -> Result<()> {
+        ensure!(
+            self.path.is_some() || self.git.is_some() || self.version.is_some(),
+            "at least one of `path`, `git` or `version` must be specified"
+        );
+        if let Some(path) = &self.path {
+            ensure_abs_path(&path)?;
+        }
+        ensure!(
+            self.path.is_none() || self.git.is_none(),
+            "`path` and `git` cannot be specified together"
+        );
+        let git_refs = [&self.branch, &self.tag, &self.rev]
+            .iter()
+            .filter(|v| v.is_some())
+            .count();
+        ensure!(
+            git_refs <= 1,
+            "only one of `branch`, `tag` or `rev` can be specified"
+        );
+        ensure!(
+            git_refs == 0 || self.git.is_some(),
+            "`branch`, `tag` and `rev` require `git` to be specified"
+        );
+        Ok(())
+    }
+
+    fn to_inline_table(&self) -> Result<toml_edit::InlineTable> {
+        let mut table = toml_edit::InlineTable::default();
+        if let Some(path) = &self.path {
+            table.get_or_insert("path", toml_edit::Value::from(to_utf8_str(path)?.to_string()));
+        }
+        if let Some(git) = &self.git {
+            table.get_or_insert("git", toml_edit::Value::from(git.clone()));
+        }
+        if let Some(branch) = &self.branch {
+            table.get_or_insert("branch", toml_edit::Value::from(branch.clone()));
+        }
+        if let Some(tag) = &self.tag {
+            table.get_or_insert("tag", toml_edit::Value::from(tag.clone()));
+        }
+        if let Some(rev) = &self.rev {
+            table.get_or_insert("rev", toml_edit::Value::from(rev.clone()));
+        }
+        if let Some(version) = &self.version {
+            table.get_or_insert("version", toml_edit::Value::from(version.clone()));
+        }
+        Ok(table)
+    }
+}
 
 #[derive(Debug)]
 pub struct Manifest {
     data: Document,
+    original: String,
 }
 
 impl Manifest {
@@ -22,11 +94,12 @@ impl Manifest {
         ensure_is_file(&src)?;
 
         let src = src.as_ref();
-        let data = fs::read_to_string(src)
-            .with_context(|| format!("failed to read file {}", src.display()))?
+        let original = fs::read_to_string(src)
+            .with_context(|| format!("failed to read file {}", src.display()))?;
+        let data = original
             .parse::<Document>()
             .with_context(|| format!("failed to parse toml file {}", src.display()))?;
-        Ok(Self { data })
+        Ok(Self { data, original })
     }
 
     pub(crate) fn write_to(&self, dst: &impl AsRef<Path>) -> Result<()> {
@@ -39,13 +112,24 @@ impl Manifest {
         Ok(())
     }
 
+    /// Whether the in-memory manifest differs from what was originally read from disk.
+    pub(crate) fn has_changes(&self) -> bool {
+        self.data.to_string() != self.original
+    }
+
+    /// A unified diff between the manifest as originally read from disk and its current,
+    /// in-memory state.
+    pub(crate) fn diff(&self) -> String {
+        TextDiff::from_lines(&self.original, &self.data.to_string())
+            .unified_diff()
+            .header("original", "modified")
+            .to_string()
+    }
+
     pub(crate) fn add_member(&mut self, path: impl AsRef<Path>) -> Result<()> {
         ensure_abs_path(&path)?;
 
-        let path = path.as_ref();
-        let path = path
-            .to_str()
-            .ok_or_else(|| anyhow!("fail to convert to UTF-8 string {}", path.display()))?;
+        let path = to_utf8_str(path.as_ref())?;
         let members = self.get_workspace_members_section()?;
         let members_array_mut = members.as_array_mut().unwrap();
         if members_array_mut
@@ -59,6 +143,63 @@ impl Manifest {
         Ok(())
     }
 
+    pub(crate) fn remove_member(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        ensure_abs_path(&path)?;
+        let path = to_utf8_str(path.as_ref())?.to_string();
+
+        let is_empty = match self.workspace_members_mut() {
+            Some(members_array_mut) => {
+                let index = members_array_mut
+                    .iter()
+                    .position(|v| v.as_str().unwrap() == path);
+                if let Some(index) = index {
+                    members_array_mut.remove(index);
+                }
+                members_array_mut.is_empty()
+            }
+            None => return Ok(()),
+        };
+
+        if is_empty {
+            if let Some(workspace) = self
+                .data
+                .get_mut(WORKSPACE_KEY)
+                .and_then(toml_edit::Item::as_table_mut)
+            {
+                workspace.remove(MEMBERS_KEY);
+            }
+        }
+        Ok(())
+    }
+
+    /// Drops any member whose path no longer exists on disk.
+    pub(crate) fn prune_members(&mut self) -> Result<()> {
+        let stale: Vec<String> = match self.workspace_members_mut() {
+            Some(members_array_mut) => members_array_mut
+                .iter()
+                .filter_map(|v| v.as_str())
+                .filter(|path| !Path::new(path).exists())
+                .map(ToString::to_string)
+                .collect(),
+            None => return Ok(()),
+        };
+
+        for path in stale {
+            self.remove_member(path)?;
+        }
+        Ok(())
+    }
+
+    /// Looks up `[workspace.members]` without creating either section when absent, so
+    /// remove/prune operations against a manifest with no workspace are a no-op.
+    fn workspace_members_mut(&mut self) -> Option<&mut toml_edit::Array> {
+        self.data
+            .get_mut(WORKSPACE_KEY)?
+            .as_table_mut()?
+            .get_mut(MEMBERS_KEY)?
+            .as_array_mut()
+    }
+
     fn get_workspace_section(&mut self) -> Result<&mut toml_edit::Item> {
         let workspace = self.data[WORKSPACE_KEY].or_insert(toml_edit::table());
         ensure!(
@@ -79,19 +220,20 @@ impl Manifest {
         Ok(members)
     }
 
-    pub(crate) fn add_patch(&mut self, crates_name: &str, path: impl AsRef<Path>) -> Result<()> {
-        ensure!(!crates_name.is_empty(), "crate name should not be empty");
-        ensure_abs_path(&path)?;
-
-        let path = path.as_ref();
-        let path = path
-            .to_str()
-            .ok_or_else(|| anyhow!("fail to convert to UTF-8 string {}", path.display()))?;
-        let crates_io = self.get_patch_crates_io_section()?;
-        let crates_io_table_mut = crates_io.as_table_mut().unwrap();
-        let mut table = toml_edit::InlineTable::default();
-        table.get_or_insert("path", toml_edit::Value::from(path.to_string()));
-        crates_io_table_mut[crates_name] = toml_edit::Item::Value(toml_edit::Value::from(table));
+    pub(crate) fn add_patch(
+        &mut self,
+        source: &str,
+        crate_name: &str,
+        entry: &PatchEntry,
+    ) -> Result<()> {
+        ensure!(!source.is_empty(), "source should not be empty");
+        ensure!(!crate_name.is_empty(), "crate name should not be empty");
+        entry.validate()?;
+
+        let table = entry.to_inline_table()?;
+        let section = self.get_patch_source_section(source)?;
+        let section_table_mut = section.as_table_mut().unwrap();
+        section_table_mut[crate_name] = toml_edit::Item::Value(toml_edit::Value::from(table));
         Ok(())
     }
 
@@ -105,25 +247,58 @@ impl Manifest {
         Ok(patch)
     }
 
-    fn get_patch_crates_io_section(&mut self) -> Result<&mut toml_edit::Item> {
+    fn get_patch_source_section(&mut self, source: &str) -> Result<&mut toml_edit::Item> {
         let patch = self.get_patch_section()?;
-        let crates_io = patch[CRATES_IO_KEY].or_insert(toml_edit::table());
+        let section = patch[source].or_insert(toml_edit::table());
         ensure!(
-            crates_io.is_table(),
-            InvalidManifestFile(format!("{}.{}", PATCH_KEY, CRATES_IO_KEY), "table".into())
+            section.is_table(),
+            InvalidManifestFile(format!("{}.{}", PATCH_KEY, source), "table".into())
         );
 
-        Ok(crates_io)
+        Ok(section)
+    }
+
+    pub(crate) fn remove_patch(&mut self, source: &str, crate_name: &str) -> Result<()> {
+        ensure!(!source.is_empty(), "source should not be empty");
+        ensure!(!crate_name.is_empty(), "crate name should not be empty");
+
+        let section_is_empty = {
+            let section = self.get_patch_source_section(source)?;
+            let section_table_mut = section.as_table_mut().unwrap();
+            section_table_mut.remove(crate_name);
+            section_table_mut.is_empty()
+        };
+
+        if section_is_empty {
+            if let Some(patch) = self.data[PATCH_KEY].as_table_mut() {
+                patch.remove(source);
+            }
+        }
+
+        if self
+            .data[PATCH_KEY]
+            .as_table()
+            .map_or(false, toml_edit::Table::is_empty)
+        {
+            self.data.remove(PATCH_KEY);
+        }
+        Ok(())
     }
 
     #[cfg(test)]
     fn init() -> Self {
         Self {
             data: Document::new(),
+            original: String::new(),
         }
     }
 }
 
+fn to_utf8_str(path: &Path) -> Result<&str> {
+    path.to_str()
+        .ok_or_else(|| anyhow!("fail to convert to UTF-8 string {}", path.display()))
+}
+
 fn ensure_abs_path(path: &impl AsRef<Path>) -> Result<()> {
     let path = path.as_ref();
     ensure!(path.is_absolute(), "not absolute path {}", path.display());
@@ -136,6 +311,71 @@ fn ensure_is_file(path: &impl AsRef<Path>) -> Result<()> {
     Ok(())
 }
 
+/// A crate found by [`discover_crates`].
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct DiscoveredCrate {
+    pub(crate) path: PathBuf,
+    pub(crate) name: String,
+}
+
+/// Recursively walks `root`, returning every crate it finds along the way.
+///
+/// Directories named `target`, hidden directories (dot-prefixed), and nested workspaces (a
+/// directory other than `root` itself whose `Cargo.toml` has a `[workspace]` section) are not
+/// descended into.
+pub(crate) fn discover_crates(root: &impl AsRef<Path>) -> Result<Vec<DiscoveredCrate>> {
+    ensure_abs_path(root)?;
+
+    let root = root.as_ref();
+    let mut found = Vec::new();
+    walk_for_crates(root, root, &mut found)?;
+    Ok(found)
+}
+
+fn walk_for_crates(dir: &Path, root: &Path, found: &mut Vec<DiscoveredCrate>) -> Result<()> {
+    if dir != root && should_skip_dir(dir) {
+        return Ok(());
+    }
+
+    let manifest_path = dir.join("Cargo.toml");
+    if manifest_path.is_file() {
+        let doc = fs::read_to_string(&manifest_path)
+            .with_context(|| format!("failed to read file {}", manifest_path.display()))?
+            .parse::<Document>()
+            .with_context(|| format!("failed to parse toml file {}", manifest_path.display()))?;
+
+        if dir != root && doc.as_table().contains_key(WORKSPACE_KEY) {
+            return Ok(());
+        }
+
+        let name = doc
+            .get(PACKAGE_KEY)
+            .and_then(|package| package.get(PACKAGE_NAME_KEY))
+            .and_then(toml_edit::Item::as_str);
+        if let Some(name) = name {
+            found.push(DiscoveredCrate {
+                path: dir.to_path_buf(),
+                name: name.to_string(),
+            });
+        }
+    }
+
+    for entry in fs::read_dir(dir).with_context(|| format!("failed to read dir {}", dir.display()))?
+    {
+        let path = entry?.path();
+        if path.is_dir() {
+            walk_for_crates(&path, root, found)?;
+        }
+    }
+    Ok(())
+}
+
+fn should_skip_dir(dir: &Path) -> bool {
+    dir.file_name().map_or(false, |name| {
+        name == TARGET_DIR_NAME || name.to_str().map_or(false, |name| name.starts_with('.'))
+    })
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -164,6 +404,30 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_manifest_has_changes_and_diff() -> Result<()> {
+        static CONTENT: &str = r#"
+[workspace]
+members = ["/test1"]
+"#;
+
+        let temp_dir = TempDir::new()?;
+        let file = temp_dir.path().join(MANIFEST_FILENAME);
+        fs::write(&file, CONTENT)?;
+
+        let mut manifest = Manifest::read_from(&file)?;
+        assert!(!manifest.has_changes());
+        assert_eq!(manifest.diff(), "");
+
+        manifest.add_member(PathBuf::from("/test2"))?;
+        assert!(manifest.has_changes());
+        let diff = manifest.diff();
+        assert!(diff.contains("-members = [\"/test1\"]"));
+        assert!(diff.contains("+members = [\"/test1\", \"/test2\"]"));
+
+        Ok(())
+    }
+
     #[test]
     fn test_manifest_add_member_from_empty() -> Result<()> {
         static ANS: &str = r#"
@@ -255,6 +519,214 @@ members = ["/test1", "/test2/test2"]
         Ok(())
     }
 
+    #[test]
+    fn test_manifest_remove_member() -> Result<()> {
+        static CONTENT: &str = r#"
+[workspace]
+members = ["/test1", "/test2/test2"]
+"#;
+
+        static ANS: &str = r#"
+[workspace]
+members = ["/test2/test2"]
+"#;
+
+        let temp_dir = TempDir::new()?;
+        let file = temp_dir.path().join(MANIFEST_FILENAME);
+        fs::write(&file, CONTENT)?;
+
+        let mut manifest = Manifest::read_from(&file)?;
+        manifest.remove_member(PathBuf::from("/test1"))?;
+        manifest.write_to(&file)?;
+
+        let data = fs::read_to_string(file)?;
+        assert_eq!(data, ANS);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_manifest_remove_member_last_cleans_up_section() -> Result<()> {
+        static CONTENT: &str = r#"
+[workspace]
+members = ["/test1"]
+"#;
+
+        static ANS: &str = r#"
+[workspace]
+"#;
+
+        let temp_dir = TempDir::new()?;
+        let file = temp_dir.path().join(MANIFEST_FILENAME);
+        fs::write(&file, CONTENT)?;
+
+        let mut manifest = Manifest::read_from(&file)?;
+        manifest.remove_member(PathBuf::from("/test1"))?;
+        manifest.write_to(&file)?;
+
+        let data = fs::read_to_string(file)?;
+        assert_eq!(data, ANS);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_manifest_remove_member_not_present_is_noop() -> Result<()> {
+        static CONTENT: &str = r#"
+[workspace]
+members = ["/test1"]
+"#;
+
+        let temp_dir = TempDir::new()?;
+        let file = temp_dir.path().join(MANIFEST_FILENAME);
+        fs::write(&file, CONTENT)?;
+
+        let mut manifest = Manifest::read_from(&file)?;
+        manifest.remove_member(PathBuf::from("/test2"))?;
+        manifest.write_to(&file)?;
+
+        let data = fs::read_to_string(file)?;
+        assert_eq!(data, CONTENT);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_manifest_remove_member_no_workspace_is_noop() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let file = temp_dir.path().join(MANIFEST_FILENAME);
+        fs::write(&file, "")?;
+
+        let mut manifest = Manifest::read_from(&file)?;
+        manifest.remove_member(PathBuf::from("/test1"))?;
+        manifest.write_to(&file)?;
+
+        let data = fs::read_to_string(file)?;
+        assert_eq!(data, "");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_manifest_prune_members_no_workspace_is_noop() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let file = temp_dir.path().join(MANIFEST_FILENAME);
+        fs::write(&file, "")?;
+
+        let mut manifest = Manifest::read_from(&file)?;
+        manifest.prune_members()?;
+        manifest.write_to(&file)?;
+
+        let data = fs::read_to_string(file)?;
+        assert_eq!(data, "");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_manifest_prune_members() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let existing = temp_dir.path().join("exists");
+        fs::create_dir(&existing)?;
+
+        let content = format!(
+            r#"
+[workspace]
+members = ["{}", "/does/not/exist"]
+"#,
+            existing.display()
+        );
+        let ans = format!(
+            r#"
+[workspace]
+members = ["{}"]
+"#,
+            existing.display()
+        );
+
+        let file = temp_dir.path().join(MANIFEST_FILENAME);
+        fs::write(&file, &content)?;
+
+        let mut manifest = Manifest::read_from(&file)?;
+        manifest.prune_members()?;
+        manifest.write_to(&file)?;
+
+        let data = fs::read_to_string(&file)?;
+        assert_eq!(data, ans);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_manifest_remove_patch() -> Result<()> {
+        static CONTENT: &str = r#"
+[patch.crates-io]
+hoge = { path = "/hoge" }
+fuga = { path = "/fuga" }
+"#;
+
+        static ANS: &str = r#"
+[patch.crates-io]
+fuga = { path = "/fuga" }
+"#;
+
+        let temp_dir = TempDir::new()?;
+        let file = temp_dir.path().join(MANIFEST_FILENAME);
+        fs::write(&file, CONTENT)?;
+
+        let mut manifest = Manifest::read_from(&file)?;
+        manifest.remove_patch(CRATES_IO_KEY, "hoge")?;
+        manifest.write_to(&file)?;
+
+        let data = fs::read_to_string(file)?;
+        assert_eq!(data, ANS);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_manifest_remove_patch_last_cleans_up_tables() -> Result<()> {
+        static CONTENT: &str = r#"
+[patch.crates-io]
+hoge = { path = "/hoge" }
+"#;
+
+        let temp_dir = TempDir::new()?;
+        let file = temp_dir.path().join(MANIFEST_FILENAME);
+        fs::write(&file, CONTENT)?;
+
+        let mut manifest = Manifest::read_from(&file)?;
+        manifest.remove_patch(CRATES_IO_KEY, "hoge")?;
+        manifest.write_to(&file)?;
+
+        let data = fs::read_to_string(file)?;
+        assert_eq!(data, "");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_manifest_remove_patch_error() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let file = temp_dir.path().join(MANIFEST_FILENAME);
+        fs::write(&file, "")?;
+
+        let mut manifest = Manifest::read_from(&file)?;
+        // empty source
+        assert!(manifest.remove_patch("", "hoge").is_err());
+        // empty crate name
+        assert!(manifest.remove_patch(CRATES_IO_KEY, "").is_err());
+
+        Ok(())
+    }
+
+    fn path_patch(path: &str) -> PatchEntry {
+        PatchEntry {
+            path: Some(PathBuf::from(path)),
+            ..PatchEntry::default()
+        }
+    }
+
     #[test]
     fn test_manifest_add_patch_from_empty() -> Result<()> {
         static ANS: &str = r#"
@@ -268,8 +740,8 @@ fuga ={path = "/fuga/fuga"}
         fs::write(&file, "")?;
 
         let mut manifest = Manifest::read_from(&file)?;
-        manifest.add_patch("hoge", PathBuf::from("/hoge"))?;
-        manifest.add_patch("fuga", PathBuf::from("/fuga/fuga"))?;
+        manifest.add_patch(CRATES_IO_KEY, "hoge", &path_patch("/hoge"))?;
+        manifest.add_patch(CRATES_IO_KEY, "fuga", &path_patch("/fuga/fuga"))?;
         manifest.write_to(&file)?;
 
         let data = fs::read_to_string(file)?;
@@ -302,7 +774,7 @@ fuga ={path = "/fuga/fuga"}
         fs::write(&file, CONTENT)?;
 
         let mut manifest = Manifest::read_from(&file)?;
-        manifest.add_patch("fuga", PathBuf::from("/fuga/fuga"))?;
+        manifest.add_patch(CRATES_IO_KEY, "fuga", &path_patch("/fuga/fuga"))?;
         manifest.write_to(&file)?;
 
         let data = fs::read_to_string(file)?;
@@ -323,9 +795,38 @@ hoge ={path = "/piyo"}
         fs::write(&file, "")?;
 
         let mut manifest = Manifest::read_from(&file)?;
-        manifest.add_patch("hoge", PathBuf::from("/hoge"))?;
-        manifest.add_patch("hoge", PathBuf::from("/fuga"))?;
-        manifest.add_patch("hoge", PathBuf::from("/piyo"))?;
+        manifest.add_patch(CRATES_IO_KEY, "hoge", &path_patch("/hoge"))?;
+        manifest.add_patch(CRATES_IO_KEY, "hoge", &path_patch("/fuga"))?;
+        manifest.add_patch(CRATES_IO_KEY, "hoge", &path_patch("/piyo"))?;
+        manifest.write_to(&file)?;
+
+        let data = fs::read_to_string(file)?;
+        assert_eq!(data, ANS);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_manifest_add_patch_with_git_source() -> Result<()> {
+        static ANS: &str = r#"
+[patch."https://github.com/foo/bar"]
+hoge ={git = "https://github.com/foo/bar",branch = "ros2"}
+"#;
+
+        let temp_dir = TempDir::new()?;
+        let file = temp_dir.path().join(MANIFEST_FILENAME);
+        fs::write(&file, "")?;
+
+        let mut manifest = Manifest::read_from(&file)?;
+        manifest.add_patch(
+            "https://github.com/foo/bar",
+            "hoge",
+            &PatchEntry {
+                git: Some("https://github.com/foo/bar".into()),
+                branch: Some("ros2".into()),
+                ..PatchEntry::default()
+            },
+        )?;
         manifest.write_to(&file)?;
 
         let data = fs::read_to_string(file)?;
@@ -341,14 +842,126 @@ hoge ={path = "/piyo"}
         fs::write(&file, "")?;
 
         let mut manifest = Manifest::read_from(&file)?;
+        // empty source
+        assert!(manifest
+            .add_patch("", "hoge", &path_patch("/test"))
+            .is_err());
         // empty crate name
-        assert!(manifest.add_patch("", PathBuf::from("/test")).is_err());
+        assert!(manifest
+            .add_patch(CRATES_IO_KEY, "", &path_patch("/test"))
+            .is_err());
         // relative path
-        assert!(manifest.add_patch("hoge", PathBuf::from("hoge")).is_err());
-        assert!(manifest.add_patch("hoge", PathBuf::from("./hoge")).is_err());
         assert!(manifest
-            .add_patch("hoge", PathBuf::from("../hoge"))
+            .add_patch(CRATES_IO_KEY, "hoge", &path_patch("hoge"))
+            .is_err());
+        assert!(manifest
+            .add_patch(CRATES_IO_KEY, "hoge", &path_patch("./hoge"))
+            .is_err());
+        assert!(manifest
+            .add_patch(CRATES_IO_KEY, "hoge", &path_patch("../hoge"))
+            .is_err());
+        // `path` and `git` are mutually exclusive
+        assert!(manifest
+            .add_patch(
+                CRATES_IO_KEY,
+                "hoge",
+                &PatchEntry {
+                    path: Some(PathBuf::from("/hoge")),
+                    git: Some("https://github.com/foo/bar".into()),
+                    ..PatchEntry::default()
+                },
+            )
+            .is_err());
+        // `branch` requires `git`
+        assert!(manifest
+            .add_patch(
+                CRATES_IO_KEY,
+                "hoge",
+                &PatchEntry {
+                    version: Some("1.0".into()),
+                    branch: Some("ros2".into()),
+                    ..PatchEntry::default()
+                },
+            )
+            .is_err());
+        // empty entry: none of `path`, `git`, `version` specified
+        assert!(manifest
+            .add_patch(CRATES_IO_KEY, "hoge", &PatchEntry::default())
             .is_err());
+        // only one of `branch`, `tag`, `rev` can be specified
+        assert!(manifest
+            .add_patch(
+                CRATES_IO_KEY,
+                "hoge",
+                &PatchEntry {
+                    git: Some("https://github.com/foo/bar".into()),
+                    branch: Some("ros2".into()),
+                    tag: Some("v1.0.0".into()),
+                    ..PatchEntry::default()
+                },
+            )
+            .is_err());
+
+        Ok(())
+    }
+
+    fn write_package(dir: &Path, name: &str) -> Result<()> {
+        fs::create_dir_all(dir)?;
+        fs::write(
+            dir.join(MANIFEST_FILENAME),
+            format!("[package]\nname = \"{name}\"\nversion = \"0.1.0\"\n"),
+        )?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_discover_crates() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let root = temp_dir.path();
+
+        write_package(&root.join("a"), "a")?;
+        write_package(&root.join("sub").join("b"), "b")?;
+
+        // virtual workspace manifest at the discovery root: skipped, not a panic, since the
+        // `dir != root` workspace guard doesn't apply to `root` itself
+        fs::write(
+            root.join(MANIFEST_FILENAME),
+            "[workspace]\nmembers = [\"a\"]\n",
+        )?;
+
+        // skipped: build output
+        write_package(&root.join("target").join("ignored"), "ignored-target")?;
+        // skipped: hidden directory
+        write_package(&root.join(".hidden").join("ignored"), "ignored-hidden")?;
+        // skipped: nested workspace and everything below it
+        fs::create_dir_all(root.join("ws").join("c"))?;
+        fs::write(
+            root.join("ws").join(MANIFEST_FILENAME),
+            "[workspace]\nmembers = [\"c\"]\n",
+        )?;
+        write_package(&root.join("ws").join("c"), "ignored-nested-ws")?;
+
+        let mut found: Vec<(PathBuf, String)> = discover_crates(&root)?
+            .into_iter()
+            .map(|krate| (krate.path, krate.name))
+            .collect();
+        found.sort();
+
+        assert_eq!(
+            found,
+            vec![
+                (root.join("a"), "a".to_string()),
+                (root.join("sub").join("b"), "b".to_string()),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_discover_crates_error() -> Result<()> {
+        // relative path
+        assert!(discover_crates(&PathBuf::from("relative")).is_err());
 
         Ok(())
     }